@@ -3,27 +3,101 @@ pub use exgui;
 pub use gl;
 
 use std::mem;
+use std::time::{Duration, Instant};
 use glutin::{
     WindowBuilder, ContextBuilder, EventsLoop, GlWindow, GlContext, ElementState, MouseButton,
-    CreationError, ContextError,
+    MouseScrollDelta, CreationError, ContextError,
 };
 use exgui::{
-    Comp, Color, SystemMessage,
+    Comp, Color, Cursor, SystemMessage,
     renderer::Renderer,
-    controller::MouseInput,
+    controller::{MouseInput, KeyboardInput},
 };
 
+/// Maximum gap between two left-clicks, in the same spot, to count as a double-click.
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+/// Maximum pointer drift between two clicks to still count as a double-click.
+const DOUBLE_CLICK_DISTANCE: f64 = 4.0;
+/// Longest single frame time fed to the fixed-timestep accumulator, so a debugger pause or a
+/// dropped frame can't make it spiral into catching up forever.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+
+fn duration_as_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1_000_000_000.0
+}
+
+fn duration_from_secs(secs: f64) -> Duration {
+    let whole = secs.trunc();
+    let nanos = (secs - whole) * 1_000_000_000.0;
+    Duration::new(whole as u64, nanos as u32)
+}
+
+/// Maps `exgui`'s backend-agnostic cursor request onto glutin's system cursor set. Platforms
+/// that don't carry every variant (e.g. some Wayland compositors) already snap unknown ones to
+/// the nearest icon inside glutin/winit itself, so this mapping only has to pick a reasonable
+/// target, not handle that fallback itself.
+fn map_cursor(cursor: Cursor) -> glutin::MouseCursor {
+    match cursor {
+        Cursor::Default => glutin::MouseCursor::Default,
+        Cursor::Pointer => glutin::MouseCursor::Hand,
+        Cursor::Text => glutin::MouseCursor::Text,
+        Cursor::Crosshair => glutin::MouseCursor::Crosshair,
+        Cursor::Move => glutin::MouseCursor::Move,
+        Cursor::Grab => glutin::MouseCursor::Grab,
+        Cursor::Grabbing => glutin::MouseCursor::Grabbing,
+        Cursor::NotAllowed => glutin::MouseCursor::NotAllowed,
+        Cursor::ResizeE => glutin::MouseCursor::EResize,
+        Cursor::ResizeN => glutin::MouseCursor::NResize,
+        Cursor::ResizeNe => glutin::MouseCursor::NeResize,
+        Cursor::ResizeNw => glutin::MouseCursor::NwResize,
+        Cursor::ResizeS => glutin::MouseCursor::SResize,
+        Cursor::ResizeSe => glutin::MouseCursor::SeResize,
+        Cursor::ResizeSw => glutin::MouseCursor::SwResize,
+        Cursor::ResizeW => glutin::MouseCursor::WResize,
+        Cursor::ResizeEw => glutin::MouseCursor::EwResize,
+        Cursor::ResizeNs => glutin::MouseCursor::NsResize,
+        Cursor::ResizeNesw => glutin::MouseCursor::NeswResize,
+        Cursor::ResizeNwse => glutin::MouseCursor::NwseResize,
+    }
+}
+
 pub enum AppState {
     Exit,
     Continue,
 }
 
+/// Controls how often `run_proc` redraws the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedrawMode {
+    /// Render every iteration of the loop, as fast as `poll_events` allows. Required for
+    /// animated UIs driven by `SystemMessage::FrameChange`.
+    Continuous,
+    /// Only render when an event arrived or a redraw was explicitly requested via
+    /// `App::request_redraw`. Between redraws the loop blocks instead of spinning, so idle,
+    /// form-style UIs cost close to no CPU.
+    OnDemand,
+}
+
+/// RGBA8 pixels read back from the GL color buffer, row 0 first (top of the image).
+pub struct ImageBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
 pub struct App<R: Renderer> {
     events_loop: Option<EventsLoop>,
     window: GlWindow,
     renderer: R,
     background_color: Color,
     exit_by_escape: bool,
+    redraw_mode: RedrawMode,
+    redraw_requested: bool,
+    fixed_dt: Option<Duration>,
+    fps: f32,
+    interpolation_alpha: f32,
+    cursor_icon: Cursor,
+    scale_factor: f64,
     width: u32,
     height: u32,
 }
@@ -35,6 +109,7 @@ pub enum AppError<RE> {
     RendererError(RE),
     WindowNoLongerExists,
     EventsLoopIsNone,
+    FramebufferIncomplete,
 }
 
 impl<RE> From<CreationError> for AppError<RE> {
@@ -59,6 +134,7 @@ impl<R: Renderer> App<R> {
         let events_loop = EventsLoop::new();
         let (width, height) = window_builder.window.max_dimensions.unwrap_or((0, 0));
         let window = GlWindow::new(window_builder, context_builder, &events_loop)?;
+        let scale_factor = window.get_hidpi_factor();
         Ok(App {
             events_loop: Some(events_loop),
             window,
@@ -67,6 +143,13 @@ impl<R: Renderer> App<R> {
             width,
             height,
             exit_by_escape: true,
+            redraw_mode: RedrawMode::Continuous,
+            redraw_requested: false,
+            fixed_dt: None,
+            fps: 0.0,
+            interpolation_alpha: 1.0,
+            cursor_icon: Cursor::Default,
+            scale_factor,
         })
     }
 
@@ -80,6 +163,146 @@ impl<R: Renderer> App<R> {
         self
     }
 
+    pub fn with_redraw_mode(mut self, redraw_mode: RedrawMode) -> Self {
+        self.redraw_mode = redraw_mode;
+        self
+    }
+
+    /// Runs the component's `SystemMessage::FrameChange` on a fixed-interval accumulator (`hz`
+    /// updates per second) instead of once per rendered frame, so animation/physics stay stable
+    /// independent of the display's vsync rate.
+    pub fn with_update_rate(mut self, hz: f64) -> Self {
+        self.fixed_dt = Some(duration_from_secs(1.0 / hz));
+        self
+    }
+
+    /// Frames rendered per second, measured over the most recent frame.
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+
+    /// In `with_update_rate` mode, how far the accumulator is into the *next* fixed step
+    /// (0.0 = just ran an update, 1.0 = about to run another). Blend the previous and current
+    /// simulation state by this factor when rendering to avoid stutter when `hz` doesn't evenly
+    /// divide the display's refresh rate. Always `1.0` when no fixed update rate is set.
+    pub fn interpolation_alpha(&self) -> f32 {
+        self.interpolation_alpha
+    }
+
+    /// Sets the OS cursor icon, skipping the call to the window if it's already showing `cursor`.
+    pub fn set_cursor_icon(&mut self, cursor: Cursor) {
+        if cursor == self.cursor_icon {
+            return;
+        }
+        self.window.set_cursor(map_cursor(cursor));
+        self.cursor_icon = cursor;
+    }
+
+    /// The window's current logical-to-physical pixel ratio (1.0 on standard-DPI displays).
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Re-reads the window's DPI scale factor and, if it changed, propagates it to the renderer.
+    /// Needed on top of `HiDpiFactorChanged` because some platforms only report the new factor
+    /// via a plain `Resized`/`Moved` when a window crosses onto a different monitor.
+    fn sync_scale_factor(&mut self) {
+        let factor = self.window.get_hidpi_factor();
+        self.set_scale_factor(factor);
+    }
+
+    /// Updates the tracked scale factor and, if it changed, propagates it to the renderer.
+    fn set_scale_factor(&mut self, factor: f64) {
+        if factor != self.scale_factor {
+            self.scale_factor = factor;
+            self.renderer.set_scale_factor(factor);
+        }
+    }
+
+    /// Reads the currently bound GL color buffer into RGBA8 pixels, flipping rows so that
+    /// `data[0]` is the top-left pixel (GL's origin is bottom-left). Call this right after a
+    /// render pass, while the frame it should capture is still in the buffer.
+    pub fn capture_frame(&mut self) -> Result<ImageBuffer, AppError<R::Error>> {
+        let (width, height) = (self.width, self.height);
+        let stride = (width * 4) as usize;
+        let mut data = vec![0u8; stride * height as usize];
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0, 0, width as i32, height as i32,
+                gl::RGBA, gl::UNSIGNED_BYTE,
+                data.as_mut_ptr() as *mut _,
+            );
+        }
+        for row in 0..(height as usize / 2) {
+            let bottom_row = height as usize - 1 - row;
+            let (top, bottom) = data.split_at_mut(bottom_row * stride);
+            top[row * stride..row * stride + stride].swap_with_slice(&mut bottom[..stride]);
+        }
+        Ok(ImageBuffer { width, height, data })
+    }
+
+    /// Renders `comp` into an offscreen framebuffer sized `self.width`/`self.height` and reads
+    /// the result back, without presenting anything to the window. Useful for automated visual
+    /// tests, thumbnails, and server-side rendering of an `exgui` tree.
+    pub fn render_headless(&mut self, comp: &mut Comp) -> Result<ImageBuffer, AppError<R::Error>> {
+        let (width, height) = (self.width, self.height);
+        let (mut fbo, mut color_rb, mut depth_rb) = (0, 0, 0);
+        unsafe {
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+
+            gl::GenRenderbuffers(1, &mut color_rb);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, color_rb);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width as i32, height as i32);
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, color_rb,
+            );
+
+            gl::GenRenderbuffers(1, &mut depth_rb);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, depth_rb);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width as i32, height as i32);
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, depth_rb,
+            );
+
+            let complete = gl::CheckFramebufferStatus(gl::FRAMEBUFFER) == gl::FRAMEBUFFER_COMPLETE;
+            if !complete {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+                gl::DeleteRenderbuffers(1, &color_rb);
+                gl::DeleteRenderbuffers(1, &depth_rb);
+                gl::DeleteFramebuffers(1, &fbo);
+                return Err(AppError::FramebufferIncomplete);
+            }
+
+            gl::Viewport(0, 0, width as i32, height as i32);
+            let color = self.background_color.as_arr();
+            gl::ClearColor(color[0], color[1], color[2], color[3]);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+        }
+
+        let render_result = match comp.view_node_as_drawable_mut() {
+            Some(node) => self.renderer.render(node).map_err(|e| AppError::RendererError(e)),
+            None => Ok(()),
+        };
+        let image = render_result.and_then(|()| self.capture_frame());
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::DeleteRenderbuffers(1, &color_rb);
+            gl::DeleteRenderbuffers(1, &depth_rb);
+            gl::DeleteFramebuffers(1, &fbo);
+        }
+
+        image
+    }
+
+    /// Wakes the loop for one more redraw. Only meaningful in `RedrawMode::OnDemand`, where the
+    /// loop otherwise blocks until the next window or input event.
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
     pub fn init(&mut self) -> Result<&mut Self, AppError<R::Error>> {
         unsafe {
             self.window.make_current()?;
@@ -100,60 +323,170 @@ impl<R: Renderer> App<R> {
         -> Result<(), AppError<R::Error>>
     {
         let mut mouse_controller = MouseInput::new();
+        let mut keyboard_controller = KeyboardInput::new();
+        let mut cursor_inside = false;
+        let mut last_left_click: Option<(Instant, f64, f64)> = None;
         let mut events_loop = mem::replace(&mut self.events_loop, None)
             .ok_or(AppError::EventsLoopIsNone)?;
         let mut running = true;
+        let mut needs_redraw = true;
+        let mut last_instant = Instant::now();
+        let mut accumulator = Duration::new(0, 0);
         loop {
-            events_loop.poll_events(|event| match event {
-                glutin::Event::WindowEvent { event, .. } => {
+            let redraw_mode = self.redraw_mode;
+            let redraw_requested = mem::replace(&mut self.redraw_requested, false);
+            {
+                let mut dispatch = |event| if let glutin::Event::WindowEvent { event, .. } = event {
+                    needs_redraw = true;
                     match event {
                         glutin::WindowEvent::Closed  => running = false,
-                        glutin::WindowEvent::KeyboardInput {
-                            input: glutin::KeyboardInput {
-                                virtual_keycode: Some(glutin::VirtualKeyCode::Escape),
-                                ..
-                            },
-                            ..
-                        } if self.exit_by_escape => running = false,
-                        glutin::WindowEvent::Resized(w, h) => self.window.resize(w, h),
+                        glutin::WindowEvent::KeyboardInput { input, .. } => {
+                            keyboard_controller.key_event(
+                                comp, input.virtual_keycode, input.state, input.modifiers,
+                            );
+
+                            if self.exit_by_escape
+                                && input.state == ElementState::Pressed
+                                && input.virtual_keycode == Some(glutin::VirtualKeyCode::Escape)
+                            {
+                                running = false;
+                            }
+                        },
+                        glutin::WindowEvent::ReceivedCharacter(c) => {
+                            keyboard_controller.char_event(comp, c);
+                        },
+                        glutin::WindowEvent::Focused(focused) => {
+                            if !focused {
+                                keyboard_controller.clear_modifiers();
+                            }
+                        },
+                        glutin::WindowEvent::Resized(w, h) => {
+                            self.window.resize(w, h);
+                            self.sync_scale_factor();
+                        },
+                        glutin::WindowEvent::Moved(_, _) => self.sync_scale_factor(),
+                        glutin::WindowEvent::HiDpiFactorChanged(factor) => {
+                            self.set_scale_factor(factor);
+                        },
                         glutin::WindowEvent::CursorMoved { position: (x_pos, y_pos), .. } => {
-                            mouse_controller.update_pos(x_pos, y_pos);
+                            if !cursor_inside {
+                                cursor_inside = true;
+                                mouse_controller.hover_enter(comp);
+                            }
+                            let scale_factor = self.scale_factor;
+                            mouse_controller.update_pos(x_pos * scale_factor, y_pos * scale_factor);
+                            self.set_cursor_icon(mouse_controller.hovered_cursor(comp));
+                        },
+                        glutin::WindowEvent::CursorLeft { .. } => {
+                            if cursor_inside {
+                                cursor_inside = false;
+                                mouse_controller.hover_leave(comp);
+                                self.set_cursor_icon(Cursor::Default);
+                            }
+                        },
+                        glutin::WindowEvent::MouseInput { state, button, .. } => {
+                            mouse_controller.button_event(comp, button, state);
+
+                            if state == ElementState::Pressed && button == MouseButton::Left {
+                                let (x, y) = mouse_controller.pos();
+                                // `pos()` is in physical pixels but the tolerance below is meant
+                                // in logical ones, so scale it up to match.
+                                let double_click_distance = DOUBLE_CLICK_DISTANCE * self.scale_factor;
+                                let is_double = last_left_click
+                                    .map(|(at, lx, ly)| {
+                                        at.elapsed() < DOUBLE_CLICK_INTERVAL
+                                            && (lx - x).abs() <= double_click_distance
+                                            && (ly - y).abs() <= double_click_distance
+                                    })
+                                    .unwrap_or(false);
+
+                                if is_double {
+                                    mouse_controller.double_click(comp);
+                                    last_left_click = None;
+                                } else {
+                                    last_left_click = Some((Instant::now(), x, y));
+                                }
+                            }
                         },
-                        glutin::WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. } => {
-                            mouse_controller.left_pressed_comp(comp);
+                        glutin::WindowEvent::MouseWheel { delta, .. } => {
+                            let (dx, dy) = match delta {
+                                MouseScrollDelta::LineDelta(dx, dy) => (dx as f64, dy as f64),
+                                MouseScrollDelta::PixelDelta(dx, dy) => (dx, dy),
+                            };
+                            mouse_controller.wheel(comp, dx, dy);
                         },
                         _ => (),
                     }
+                };
+
+                events_loop.poll_events(&mut dispatch);
+
+                if running && redraw_mode == RedrawMode::OnDemand && !needs_redraw && !redraw_requested {
+                    events_loop.run_forever(|event| {
+                        dispatch(event);
+                        glutin::ControlFlow::Break
+                    });
                 }
-                _ => (),
-            });
+            }
+
+            if redraw_requested {
+                needs_redraw = true;
+            }
+            if redraw_mode == RedrawMode::Continuous {
+                needs_redraw = true;
+            }
 
             if !running {
                 break;
             }
 
-            let (width, height) = self.window.get_inner_size()
-                .ok_or(AppError::WindowNoLongerExists)?;
-            self.width = width;
-            self.height = height;
-            unsafe {
-                gl::Viewport(0, 0, width as i32, height as i32);
-                gl::Clear(
-                    gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT,
-                );
+            if needs_redraw {
+                let (logical_width, logical_height) = self.window.get_inner_size()
+                    .ok_or(AppError::WindowNoLongerExists)?;
+                self.width = (logical_width as f64 * self.scale_factor).round() as u32;
+                self.height = (logical_height as f64 * self.scale_factor).round() as u32;
+                unsafe {
+                    gl::Viewport(0, 0, self.width as i32, self.height as i32);
+                    gl::Clear(
+                        gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT,
+                    );
+                }
             }
 
             if let AppState::Exit = proc(self, comp) {
                 break;
             }
 
-            comp.send_system(SystemMessage::FrameChange);
+            let now = Instant::now();
+            let mut frame_time = now.duration_since(last_instant);
+            if frame_time > MAX_FRAME_TIME {
+                frame_time = MAX_FRAME_TIME;
+            }
+            last_instant = now;
+            let frame_time_secs = duration_as_secs(frame_time);
+            if frame_time_secs > 0.0 {
+                self.fps = 1.0 / frame_time_secs as f32;
+            }
 
-            if let Some(node) = comp.view_node_as_drawable_mut() {
-                self.renderer.render(node).map_err(|e| AppError::RendererError(e))?;
+            if let Some(fixed_dt) = self.fixed_dt {
+                accumulator += frame_time;
+                while accumulator >= fixed_dt {
+                    comp.send_system(SystemMessage::FrameChange { dt: duration_as_secs(fixed_dt) });
+                    accumulator -= fixed_dt;
+                }
+                self.interpolation_alpha = (duration_as_secs(accumulator) / duration_as_secs(fixed_dt)) as f32;
+            } else {
+                comp.send_system(SystemMessage::FrameChange { dt: frame_time_secs });
             }
 
-            self.window.swap_buffers()?;
+            if needs_redraw {
+                if let Some(node) = comp.view_node_as_drawable_mut() {
+                    self.renderer.render(node).map_err(|e| AppError::RendererError(e))?;
+                }
+
+                self.window.swap_buffers()?;
+                needs_redraw = false;
+            }
         }
         mem::replace(&mut self.events_loop, Some(events_loop));
 